@@ -0,0 +1,276 @@
+//! A `TypedArena`-style bump allocator for `Hierarchy` objects.
+//!
+//! Unlike [`Gc`](crate::gc::Gc), objects allocated here are never traced or
+//! individually freed: the whole arena is torn down at once when it is
+//! dropped. This suits large transient object trees (parser/AST nodes) that
+//! don't need per-node deallocation.
+
+use alloc::alloc::{alloc, dealloc, handle_alloc_error, Layout};
+use alloc::vec::Vec;
+use core::cell::{Cell, RefCell};
+use core::marker::PhantomData;
+use core::mem;
+use core::ops::Deref;
+use core::ptr::NonNull;
+
+use crate::{Handle, Hierarchy, ObjectPtr, Pointed, SameObjectPtr};
+
+const FIRST_CHUNK_CAPACITY: usize = 4096;
+
+struct Chunk {
+    ptr: NonNull<u8>,
+    capacity: usize,
+    align: usize,
+    len: Cell<usize>,
+}
+
+impl Chunk {
+    /// `align` becomes the chunk's base alignment, so `aligned_offset` only
+    /// ever needs to round the in-chunk offset, not the absolute address.
+    /// Every allocation placed in this chunk must request at most `align`.
+    fn new(capacity: usize, align: usize) -> Self {
+        let layout = Layout::from_size_align(capacity, align).unwrap();
+        let ptr = unsafe { alloc(layout) };
+        let ptr = match NonNull::new(ptr) {
+            Some(ptr) => ptr,
+            None => handle_alloc_error(layout),
+        };
+        Self {
+            ptr,
+            capacity,
+            align,
+            len: Cell::new(0),
+        }
+    }
+
+    fn aligned_offset(&self, align: usize) -> usize {
+        (self.len.get() + align - 1) & !(align - 1)
+    }
+
+    fn fits(&self, size: usize, align: usize) -> bool {
+        align <= self.align && self.aligned_offset(align).saturating_add(size) <= self.capacity
+    }
+
+    fn alloc<T>(&self, value: T) -> NonNull<T> {
+        let offset = self.aligned_offset(mem::align_of::<T>());
+        let ptr = unsafe { self.ptr.as_ptr().add(offset).cast::<T>() };
+        unsafe { ptr.write(value) };
+        self.len.set(offset + mem::size_of::<T>());
+        unsafe { NonNull::new_unchecked(ptr) }
+    }
+}
+
+impl Drop for Chunk {
+    fn drop(&mut self) {
+        let layout = Layout::from_size_align(self.capacity, self.align).unwrap();
+        unsafe { dealloc(self.ptr.as_ptr(), layout) };
+    }
+}
+
+unsafe fn drop_glue<T>(ptr: NonNull<u8>) {
+    unsafe { core::ptr::drop_in_place(ptr.cast::<T>().as_ptr()) };
+}
+
+/// A bump arena. Objects allocated from it live until the arena itself is
+/// dropped, at which point every allocation's destructor runs.
+pub struct TypedArena {
+    chunks: RefCell<Vec<Chunk>>,
+    drops: RefCell<Vec<(NonNull<u8>, unsafe fn(NonNull<u8>))>>,
+}
+
+impl Default for TypedArena {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TypedArena {
+    pub const fn new() -> Self {
+        Self {
+            chunks: RefCell::new(Vec::new()),
+            drops: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Moves `value` into the arena and returns a `Handle` borrowing it for
+    /// as long as the arena is borrowed.
+    pub fn alloc<T: Hierarchy>(&self, value: T) -> Handle<ArenaRef<'_, T>> {
+        Handle::from(ArenaRef {
+            ptr: self.alloc_raw(value),
+            _marker: PhantomData,
+        })
+    }
+
+    fn alloc_raw<T>(&self, value: T) -> NonNull<T> {
+        let size = mem::size_of::<T>();
+        let align = mem::align_of::<T>();
+
+        let mut chunks = self.chunks.borrow_mut();
+        if chunks.last().is_none_or(|chunk| !chunk.fits(size, align)) {
+            let capacity = chunks
+                .last()
+                .map_or(FIRST_CHUNK_CAPACITY, |chunk| chunk.capacity * 2)
+                .max(size.next_power_of_two().max(1));
+            chunks.push(Chunk::new(capacity, align));
+        }
+
+        let ptr = chunks.last().unwrap().alloc(value);
+        self.drops.borrow_mut().push((ptr.cast(), drop_glue::<T>));
+        ptr
+    }
+}
+
+impl Drop for TypedArena {
+    fn drop(&mut self) {
+        for (ptr, drop) in self.drops.get_mut().drain(..).rev() {
+            unsafe { drop(ptr) };
+        }
+    }
+}
+
+/// An [`ObjectPtr`] borrowing storage owned by a [`TypedArena`]. `from_raw`
+/// and `into_raw` are no-ops: the arena, not the pointer, owns the object.
+pub struct ArenaRef<'a, T: Hierarchy> {
+    ptr: NonNull<T>,
+    _marker: PhantomData<&'a T>,
+}
+
+impl<'a, T: Hierarchy> Clone for ArenaRef<'a, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, T: Hierarchy> Copy for ArenaRef<'a, T> {}
+
+impl<'a, T: Hierarchy> Deref for ArenaRef<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+unsafe impl<'a, T: Hierarchy> ObjectPtr for ArenaRef<'a, T> {
+    unsafe fn from_raw(ptr: NonNull<Pointed<Self>>) -> Self {
+        ArenaRef {
+            ptr,
+            _marker: PhantomData,
+        }
+    }
+
+    fn into_raw(self) -> NonNull<Pointed<Self>> {
+        self.ptr
+    }
+}
+
+unsafe impl<'a, 'b, T: Hierarchy, U: Hierarchy> SameObjectPtr<ArenaRef<'b, U>> for ArenaRef<'a, T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Object, Virtual, VirtualStub};
+    use alloc::rc::Rc;
+    use core::ops::DerefMut;
+
+    struct Node {
+        base: Object,
+        id: u32,
+        dropped: Rc<Cell<usize>>,
+    }
+
+    impl Deref for Node {
+        type Target = Object;
+
+        fn deref(&self) -> &Object {
+            &self.base
+        }
+    }
+
+    impl DerefMut for Node {
+        fn deref_mut(&mut self) -> &mut Object {
+            &mut self.base
+        }
+    }
+
+    impl VirtualStub for Node {}
+
+    impl Virtual for Node {
+        type Dyn = dyn VirtualStub;
+        const TABLE: Self::VTable = crate::vt!();
+    }
+
+    impl Drop for Node {
+        fn drop(&mut self) {
+            self.dropped.set(self.dropped.get() + 1);
+        }
+    }
+
+    #[repr(align(64))]
+    struct AlignedNode {
+        base: Object,
+    }
+
+    impl Deref for AlignedNode {
+        type Target = Object;
+
+        fn deref(&self) -> &Object {
+            &self.base
+        }
+    }
+
+    impl DerefMut for AlignedNode {
+        fn deref_mut(&mut self) -> &mut Object {
+            &mut self.base
+        }
+    }
+
+    impl VirtualStub for AlignedNode {}
+
+    impl Virtual for AlignedNode {
+        type Dyn = dyn VirtualStub;
+        const TABLE: Self::VTable = crate::vt!();
+    }
+
+    #[test]
+    fn drop_runs_every_destructor_across_chunk_growth() {
+        let dropped = Rc::new(Cell::new(0));
+        let arena = TypedArena::new();
+
+        const COUNT: u32 = 1024;
+        for id in 0..COUNT {
+            let handle = arena.alloc(Node {
+                base: Object,
+                id,
+                dropped: dropped.clone(),
+            });
+            assert_eq!(handle.id, id);
+        }
+
+        assert!(
+            arena.chunks.borrow().len() > 1,
+            "test should allocate enough to grow past the first chunk"
+        );
+
+        drop(arena);
+        assert_eq!(dropped.get(), COUNT as usize);
+    }
+
+    #[test]
+    fn over_aligned_allocations_land_on_an_aligned_address() {
+        let arena = TypedArena::new();
+
+        // Misalign the bump offset with a spacer before the over-aligned
+        // allocation, so only a correctly-aligned chunk base (not a lucky
+        // offset) can make the next allocation line up.
+        let _spacer = arena.alloc(Node {
+            base: Object,
+            id: 0,
+            dropped: Rc::new(Cell::new(0)),
+        });
+        let handle = arena.alloc(AlignedNode { base: Object });
+
+        let addr = &*handle as *const AlignedNode as usize;
+        assert_eq!(addr % mem::align_of::<AlignedNode>(), 0);
+    }
+}
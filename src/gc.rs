@@ -0,0 +1,269 @@
+//! A mark-and-sweep [`Heap`] for cyclic `Hierarchy` object graphs.
+//!
+//! `Gc<T>` is an [`ObjectPtr`] like `&T` or `&mut T`, but the object it
+//! points to lives in a [`Heap`] instead of on the stack, and is reclaimed by
+//! collection rather than by scope. This makes it suitable for graphs with
+//! cycles (e.g. parent/child node pairs) that `Box`/`Rc` cannot free on
+//! their own.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::cell::Cell;
+use core::mem;
+use core::ops::{Deref, DerefMut};
+use core::ptr::NonNull;
+
+use crate::{Hierarchy, ObjectPtr, Pointed, SameObjectPtr};
+
+struct Header {
+    mark: Cell<bool>,
+    next: Option<NonNull<Header>>,
+    trace: unsafe fn(NonNull<Header>, &mut Tracer),
+    drop: unsafe fn(NonNull<Header>),
+}
+
+#[repr(C)]
+struct Allocation<T> {
+    header: Header,
+    value: T,
+}
+
+fn header_of<T>(ptr: NonNull<T>) -> NonNull<Header> {
+    let offset = mem::offset_of!(Allocation<T>, value);
+    unsafe { NonNull::new_unchecked(ptr.as_ptr().cast::<u8>().sub(offset).cast()) }
+}
+
+unsafe fn trace_impl<T: Trace>(header: NonNull<Header>, tracer: &mut Tracer) {
+    let alloc = header.cast::<Allocation<T>>();
+    unsafe { (*alloc.as_ptr()).value.trace(tracer) };
+}
+
+unsafe fn drop_impl<T>(header: NonNull<Header>) {
+    let alloc = header.cast::<Allocation<T>>();
+    drop(unsafe { Box::from_raw(alloc.as_ptr()) });
+}
+
+/// A pointer into a [`Heap`], reclaimed by [`Heap::collect`] rather than by
+/// `Drop`.
+pub struct Gc<T: Hierarchy> {
+    ptr: NonNull<T>,
+}
+
+impl<T: Hierarchy> Clone for Gc<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T: Hierarchy> Copy for Gc<T> {}
+
+impl<T: Hierarchy> Deref for Gc<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+unsafe impl<T: Hierarchy> ObjectPtr for Gc<T> {
+    unsafe fn from_raw(ptr: NonNull<Pointed<Self>>) -> Self {
+        Gc { ptr }
+    }
+
+    fn into_raw(self) -> NonNull<Pointed<Self>> {
+        self.ptr
+    }
+}
+
+unsafe impl<T: Hierarchy, U: Hierarchy> SameObjectPtr<Gc<U>> for Gc<T> {}
+
+/// Implemented by anything a [`Heap`] can allocate: pushes every `Gc` it
+/// directly holds onto `tracer`'s worklist.
+pub trait Trace {
+    fn trace(&self, tracer: &mut Tracer);
+}
+
+impl<T: DerefMut<Target: Trace>> Trace for T {
+    default fn trace(&self, tracer: &mut Tracer) {
+        (**self).trace(tracer)
+    }
+}
+
+impl Trace for crate::Object {
+    fn trace(&self, _tracer: &mut Tracer) {}
+}
+
+/// The worklist driving a single collection pass.
+pub struct Tracer {
+    worklist: Vec<NonNull<Header>>,
+}
+
+impl Tracer {
+    pub fn push<T: Hierarchy>(&mut self, gc: Gc<T>) {
+        self.worklist.push(header_of(gc.ptr));
+    }
+}
+
+/// An intrusive list of allocations, collected by mark-and-sweep.
+pub struct Heap {
+    head: Option<NonNull<Header>>,
+    roots: Vec<NonNull<Header>>,
+}
+
+impl Default for Heap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Heap {
+    pub const fn new() -> Self {
+        Self {
+            head: None,
+            roots: Vec::new(),
+        }
+    }
+
+    /// Boxes `value` on the heap and returns a `Gc` pointing at it. The
+    /// allocation is only ever freed by [`Heap::collect`].
+    pub fn insert<T: Hierarchy + Trace>(&mut self, value: T) -> Gc<T> {
+        let ptr = NonNull::from(Box::leak(Box::new(Allocation {
+            header: Header {
+                mark: Cell::new(false),
+                next: self.head,
+                trace: trace_impl::<T>,
+                drop: drop_impl::<T>,
+            },
+            value,
+        })));
+        self.head = Some(ptr.cast());
+        Gc {
+            ptr: unsafe { NonNull::new_unchecked(core::ptr::addr_of_mut!((*ptr.as_ptr()).value)) },
+        }
+    }
+
+    /// Registers `gc` as a root: it and everything reachable from it survive
+    /// every subsequent [`Heap::collect`] until it is passed to
+    /// [`Heap::unregister_root`].
+    pub fn register_root<T: Hierarchy>(&mut self, gc: Gc<T>) {
+        self.roots.push(header_of(gc.ptr));
+    }
+
+    pub fn unregister_root<T: Hierarchy>(&mut self, gc: Gc<T>) {
+        let header = header_of(gc.ptr);
+        if let Some(pos) = self.roots.iter().position(|r| *r == header) {
+            self.roots.swap_remove(pos);
+        }
+    }
+
+    /// Marks everything reachable from the registered roots, then frees
+    /// every unmarked allocation.
+    pub fn collect(&mut self) {
+        let mut tracer = Tracer {
+            worklist: self.roots.clone(),
+        };
+        while let Some(header) = tracer.worklist.pop() {
+            let already_marked = unsafe { header.as_ref().mark.replace(true) };
+            if already_marked {
+                continue;
+            }
+            let trace = unsafe { header.as_ref().trace };
+            unsafe { trace(header, &mut tracer) };
+        }
+
+        let mut slot = &mut self.head;
+        while let Some(header) = *slot {
+            let marked = unsafe { header.as_ref().mark.replace(false) };
+            if marked {
+                slot = unsafe { &mut (*header.as_ptr()).next };
+            } else {
+                let next = unsafe { header.as_ref().next };
+                let drop = unsafe { header.as_ref().drop };
+                unsafe { drop(header) };
+                *slot = next;
+            }
+        }
+    }
+}
+
+impl Drop for Heap {
+    fn drop(&mut self) {
+        self.roots.clear();
+        self.collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Object, Virtual, VirtualStub};
+    use alloc::rc::Rc;
+
+    struct Node {
+        base: Object,
+        child: Cell<Option<Gc<Node>>>,
+        dropped: Rc<Cell<usize>>,
+    }
+
+    impl Deref for Node {
+        type Target = Object;
+
+        fn deref(&self) -> &Object {
+            &self.base
+        }
+    }
+
+    impl DerefMut for Node {
+        fn deref_mut(&mut self) -> &mut Object {
+            &mut self.base
+        }
+    }
+
+    impl VirtualStub for Node {}
+
+    impl Virtual for Node {
+        type Dyn = dyn VirtualStub;
+        const TABLE: Self::VTable = crate::vt!();
+    }
+
+    impl Trace for Node {
+        fn trace(&self, tracer: &mut Tracer) {
+            if let Some(child) = self.child.get() {
+                tracer.push(child);
+            }
+        }
+    }
+
+    impl Drop for Node {
+        fn drop(&mut self) {
+            self.dropped.set(self.dropped.get() + 1);
+        }
+    }
+
+    #[test]
+    fn collect_frees_unreachable_cycle() {
+        let dropped = Rc::new(Cell::new(0));
+        let mut heap = Heap::new();
+
+        let a = heap.insert(Node {
+            base: Object,
+            child: Cell::new(None),
+            dropped: dropped.clone(),
+        });
+        let b = heap.insert(Node {
+            base: Object,
+            child: Cell::new(None),
+            dropped: dropped.clone(),
+        });
+        a.child.set(Some(b));
+        b.child.set(Some(a));
+
+        heap.register_root(a);
+        heap.collect();
+        assert_eq!(dropped.get(), 0, "rooted cycle must survive a collection");
+
+        heap.unregister_root(a);
+        heap.collect();
+        assert_eq!(dropped.get(), 2, "unrooted cycle must be fully collected");
+    }
+}
@@ -2,6 +2,17 @@
 #![no_std]
 #![feature(marker_trait_attr)]
 #![feature(freeze)]
+#![feature(const_type_name)]
+#![cfg_attr(feature = "gc", feature(specialization))]
+
+#[cfg(any(feature = "alloc", feature = "gc"))]
+extern crate alloc;
+
+#[cfg(feature = "gc")]
+pub mod gc;
+
+#[cfg(feature = "alloc")]
+pub mod arena;
 
 use core::any::TypeId;
 use core::fmt;
@@ -14,6 +25,13 @@ use core::ops::Deref;
 use core::ops::DerefMut;
 use core::ptr::NonNull;
 
+#[cfg(feature = "alloc")]
+use alloc::boxed::Box;
+#[cfg(feature = "alloc")]
+use alloc::rc::Rc;
+#[cfg(feature = "alloc")]
+use alloc::sync::Arc;
+
 pub type Pointed<T> = <T as Deref>::Target;
 
 pub unsafe trait ObjectPtr:
@@ -49,6 +67,48 @@ unsafe impl<T: Hierarchy> ObjectPtr for &mut T {
 
 unsafe impl<'a, T, U> SameObjectPtr<&'a mut U> for &'a mut T {}
 
+#[cfg(feature = "alloc")]
+unsafe impl<T: Hierarchy> ObjectPtr for Box<T> {
+    unsafe fn from_raw(ptr: NonNull<Pointed<Self>>) -> Self {
+        unsafe { Box::from_raw(ptr.as_ptr()) }
+    }
+
+    fn into_raw(self) -> NonNull<Pointed<Self>> {
+        unsafe { NonNull::new_unchecked(Box::into_raw(self)) }
+    }
+}
+
+#[cfg(feature = "alloc")]
+unsafe impl<T, U> SameObjectPtr<Box<U>> for Box<T> {}
+
+#[cfg(feature = "alloc")]
+unsafe impl<T: Hierarchy> ObjectPtr for Rc<T> {
+    unsafe fn from_raw(ptr: NonNull<Pointed<Self>>) -> Self {
+        unsafe { Rc::from_raw(ptr.as_ptr()) }
+    }
+
+    fn into_raw(self) -> NonNull<Pointed<Self>> {
+        unsafe { NonNull::new_unchecked(Rc::into_raw(self).cast_mut()) }
+    }
+}
+
+#[cfg(feature = "alloc")]
+unsafe impl<T, U> SameObjectPtr<Rc<U>> for Rc<T> {}
+
+#[cfg(feature = "alloc")]
+unsafe impl<T: Hierarchy> ObjectPtr for Arc<T> {
+    unsafe fn from_raw(ptr: NonNull<Pointed<Self>>) -> Self {
+        unsafe { Arc::from_raw(ptr.as_ptr()) }
+    }
+
+    fn into_raw(self) -> NonNull<Pointed<Self>> {
+        unsafe { NonNull::new_unchecked(Arc::into_raw(self).cast_mut()) }
+    }
+}
+
+#[cfg(feature = "alloc")]
+unsafe impl<T, U> SameObjectPtr<Arc<U>> for Arc<T> {}
+
 #[marker]
 pub unsafe trait Upcastable<Target: ?Sized> {}
 
@@ -68,10 +128,22 @@ fn type_id<T>() -> TypeId {
 
 pub struct ClassInfo {
     depth: u16,
+    name: &'static str,
+    type_id: fn() -> TypeId,
     downable: fn(TypeId) -> bool,
+    parent: Option<&'static ClassInfo>,
     vtable: NonNull<fn(Void)>,
 }
 
+// SAFETY: every `ClassInfo` is a `const INFO` promoted to a `'static`, and
+// `vtable` only ever points at the corresponding `'static` `Self::TABLE`
+// (see `Hierarchy`'s impls below). `Self::TABLE` is `Copy + Freeze`
+// (required by `VirtualDeref::VTable`), so the pointee is immutable for the
+// program's whole lifetime and sharing `&'static ClassInfo` across threads
+// is sound even though `NonNull` is not itself `Sync`.
+unsafe impl Sync for ClassInfo {}
+unsafe impl Send for ClassInfo {}
+
 enum Void {}
 
 pub unsafe trait Hierarchy: Virtual {
@@ -81,7 +153,10 @@ pub unsafe trait Hierarchy: Virtual {
 unsafe impl Hierarchy for Object {
     const INFO: ClassInfo = ClassInfo {
         depth: 0,
+        name: core::any::type_name::<Self>(),
+        type_id: type_id::<Self>,
         downable: |_| false,
+        parent: None,
         vtable: unsafe {
             NonNull::new_unchecked(core::ptr::from_ref(&Self::TABLE).cast_mut()).cast::<fn(Void)>()
         },
@@ -91,7 +166,10 @@ unsafe impl Hierarchy for Object {
 unsafe impl<T: DerefMut<Target: Hierarchy> + Upcastable<Object> + Virtual> Hierarchy for T {
     const INFO: ClassInfo = ClassInfo {
         depth: Pointed::<T>::INFO.depth + 1,
+        name: core::any::type_name::<Self>(),
+        type_id: type_id::<Self>,
         downable: |id| type_id::<Self>() == id || (Pointed::<T>::INFO.downable)(id),
+        parent: Some(&Pointed::<T>::INFO),
         vtable: unsafe {
             NonNull::new_unchecked(core::ptr::from_ref(&Self::TABLE).cast_mut()).cast::<fn(Void)>()
         },
@@ -204,6 +282,29 @@ impl<P: ObjectPtr<Target: Debug>> Debug for Handle<P> {
     }
 }
 
+impl<P: ObjectPtr + Clone> Clone for Handle<P> {
+    fn clone(&self) -> Self {
+        // `owned` is wrapped in `ManuallyDrop` so that if `P::clone` panics
+        // (e.g. a user `Clone` impl), unwinding does not run `owned`'s
+        // destructor and free storage `self` still owns.
+        let owned = mem::ManuallyDrop::new(unsafe { P::from_raw(self.ptr) });
+        let cloned = (*owned).clone();
+        Self {
+            ptr: cloned.into_raw(),
+            info: self.info,
+        }
+    }
+}
+
+// SAFETY: `Handle<P>` only ever grants access to `Pointed<P>` through the
+// same sharing rules `P` itself provides (a shared `&Handle` derefs to a
+// shared `&Pointed<P>`, a `Drop` recreates and drops exactly one `P`), so
+// `Handle<P>` may cross threads, or be accessed concurrently, precisely
+// when `P` can. For `P = Arc<T>` this holds whenever `T: Send + Sync`,
+// matching `Arc<T>`'s own bounds.
+unsafe impl<P: ObjectPtr + Send> Send for Handle<P> {}
+unsafe impl<P: ObjectPtr + Sync> Sync for Handle<P> {}
+
 impl<P: ObjectPtr> Drop for Handle<P> {
     fn drop(&mut self) {
         unsafe { P::from_raw(self.ptr) };
@@ -300,4 +401,278 @@ impl<P: ObjectPtr> Handle<P> {
     {
         unsafe { (self.map_to_virtual())(self.ptr).as_mut() }
     }
+
+    /// Whether the most-derived class behind this handle is (or derives
+    /// from) `U`, without needing `Q` at the call site.
+    pub fn is<U: Hierarchy>(&self) -> bool {
+        // `Object`'s own `downable` is always false, but every class derives
+        // from it.
+        type_id::<U>() == type_id::<Object>() || (self.info.downable)(type_id::<U>())
+    }
+
+    /// The `core::any::type_name` of the most-derived class behind this
+    /// handle.
+    pub fn type_name(&self) -> &'static str {
+        self.info.name
+    }
+
+    /// How many base classes the most-derived class behind this handle has.
+    pub fn depth(&self) -> u16 {
+        self.info.depth
+    }
+
+    /// Walks the base-class chain of the most-derived class behind this
+    /// handle, starting from that class itself.
+    pub fn ancestors(&self) -> Ancestors {
+        Ancestors {
+            next: Some(self.info),
+        }
+    }
+}
+
+/// Iterator over a class's base-class chain, yielding `(name, TypeId,
+/// depth)` for each level starting from the most-derived class. See
+/// [`Handle::ancestors`].
+pub struct Ancestors {
+    next: Option<&'static ClassInfo>,
+}
+
+impl Iterator for Ancestors {
+    type Item = (&'static str, TypeId, u16);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let info = self.next?;
+        self.next = info.parent;
+        Some((info.name, (info.type_id)(), info.depth))
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use super::*;
+    use alloc::rc::Rc;
+    use alloc::vec::Vec;
+    use core::cell::Cell;
+
+    struct Base {
+        obj: Object,
+        dropped: Rc<Cell<usize>>,
+    }
+
+    impl Deref for Base {
+        type Target = Object;
+
+        fn deref(&self) -> &Object {
+            &self.obj
+        }
+    }
+
+    impl DerefMut for Base {
+        fn deref_mut(&mut self) -> &mut Object {
+            &mut self.obj
+        }
+    }
+
+    impl VirtualStub for Base {}
+
+    impl Virtual for Base {
+        type Dyn = dyn VirtualStub;
+        const TABLE: Self::VTable = vt!();
+    }
+
+    impl Drop for Base {
+        fn drop(&mut self) {
+            self.dropped.set(self.dropped.get() + 1);
+        }
+    }
+
+    struct Derived {
+        base: Base,
+        dropped: Rc<Cell<usize>>,
+    }
+
+    impl Deref for Derived {
+        type Target = Base;
+
+        fn deref(&self) -> &Base {
+            &self.base
+        }
+    }
+
+    impl DerefMut for Derived {
+        fn deref_mut(&mut self) -> &mut Base {
+            &mut self.base
+        }
+    }
+
+    impl VirtualStub for Derived {}
+
+    impl Virtual for Derived {
+        type Dyn = dyn VirtualStub;
+        const TABLE: Self::VTable = vt!();
+    }
+
+    impl Drop for Derived {
+        fn drop(&mut self) {
+            self.dropped.set(self.dropped.get() + 1);
+        }
+    }
+
+    struct Sibling {
+        base: Base,
+    }
+
+    impl Deref for Sibling {
+        type Target = Base;
+
+        fn deref(&self) -> &Base {
+            &self.base
+        }
+    }
+
+    impl DerefMut for Sibling {
+        fn deref_mut(&mut self) -> &mut Base {
+            &mut self.base
+        }
+    }
+
+    impl VirtualStub for Sibling {}
+
+    impl Virtual for Sibling {
+        type Dyn = dyn VirtualStub;
+        const TABLE: Self::VTable = vt!();
+    }
+
+    #[test]
+    fn box_handle_owns_frees_and_moves_ownership_through_upcast_downcast() {
+        let dropped = Rc::new(Cell::new(0));
+        let handle: Handle<Box<Derived>> = Handle::from(Box::new(Derived {
+            base: Base {
+                obj: Object,
+                dropped: dropped.clone(),
+            },
+            dropped: dropped.clone(),
+        }));
+
+        let upcast: Handle<Box<Base>> = handle.upcast();
+
+        let upcast = match upcast.downcast::<Box<Sibling>>() {
+            Ok(_) => panic!("downcasting to an unrelated sibling type must fail"),
+            Err(handle) => handle,
+        };
+
+        let handle = upcast
+            .downcast::<Box<Derived>>()
+            .unwrap_or_else(|_| panic!("downcast to the original derived type must succeed"));
+
+        drop(handle);
+        assert_eq!(
+            dropped.get(),
+            2,
+            "Derived's and Base's destructors must each run exactly once"
+        );
+    }
+
+    #[test]
+    fn handle_arc_clone_bumps_and_drops_the_strong_count() {
+        let arc = Arc::new(Base {
+            obj: Object,
+            dropped: Rc::new(Cell::new(0)),
+        });
+        assert_eq!(Arc::strong_count(&arc), 1);
+
+        let handle: Handle<Arc<Base>> = Handle::from(arc.clone());
+        assert_eq!(Arc::strong_count(&arc), 2);
+
+        let cloned = handle.clone();
+        assert_eq!(Arc::strong_count(&arc), 3);
+
+        drop(handle);
+        assert_eq!(Arc::strong_count(&arc), 2);
+
+        drop(cloned);
+        assert_eq!(Arc::strong_count(&arc), 1);
+    }
+
+    fn assert_send<T: Send>() {}
+    fn assert_sync<T: Sync>() {}
+
+    #[test]
+    fn handle_arc_is_send_and_sync() {
+        assert_send::<Handle<Arc<Base>>>();
+        assert_sync::<Handle<Arc<Base>>>();
+    }
+
+    #[test]
+    fn handle_rc_is_not_send() {
+        // Compiles only if `Handle<Rc<Base>>` is *not* `Send`: if it were,
+        // both impls below would apply to the call and the unconstrained
+        // `A` would make it ambiguous.
+        trait AmbiguousIfSend<A> {
+            fn check() {}
+        }
+        struct Check<T: ?Sized>(PhantomData<T>);
+        impl<T: ?Sized> AmbiguousIfSend<()> for Check<T> {}
+        impl<T: ?Sized + Send> AmbiguousIfSend<u8> for Check<T> {}
+
+        Check::<Handle<Rc<Base>>>::check();
+    }
+
+    #[test]
+    fn handle_rc_is_not_sync() {
+        trait AmbiguousIfSync<A> {
+            fn check() {}
+        }
+        struct Check<T: ?Sized>(PhantomData<T>);
+        impl<T: ?Sized> AmbiguousIfSync<()> for Check<T> {}
+        impl<T: ?Sized + Sync> AmbiguousIfSync<u8> for Check<T> {}
+
+        Check::<Handle<Rc<Base>>>::check();
+    }
+
+    #[test]
+    fn ancestors_walks_the_base_chain_in_order_down_to_object() {
+        let handle: Handle<Box<Derived>> = Handle::from(Box::new(Derived {
+            base: Base {
+                obj: Object,
+                dropped: Rc::new(Cell::new(0)),
+            },
+            dropped: Rc::new(Cell::new(0)),
+        }));
+
+        let names: Vec<&'static str> = handle.ancestors().map(|(name, _, _)| name).collect();
+        assert_eq!(names, [
+            core::any::type_name::<Derived>(),
+            core::any::type_name::<Base>(),
+            core::any::type_name::<Object>(),
+        ]);
+
+        let depths: Vec<u16> = handle.ancestors().map(|(_, _, depth)| depth).collect();
+        assert_eq!(depths, [2, 1, 0]);
+
+        let mut ancestors = handle.ancestors();
+        assert_eq!(ancestors.next().unwrap().1, type_id::<Derived>());
+        assert_eq!(ancestors.next().unwrap().1, type_id::<Base>());
+        assert_eq!(ancestors.next().unwrap().1, type_id::<Object>());
+        assert!(ancestors.next().is_none());
+
+        assert_eq!(handle.depth(), 2);
+        assert_eq!(handle.type_name(), core::any::type_name::<Derived>());
+    }
+
+    #[test]
+    fn is_reports_the_whole_base_chain_but_not_unrelated_types() {
+        let handle: Handle<Box<Derived>> = Handle::from(Box::new(Derived {
+            base: Base {
+                obj: Object,
+                dropped: Rc::new(Cell::new(0)),
+            },
+            dropped: Rc::new(Cell::new(0)),
+        }));
+
+        assert!(handle.is::<Derived>());
+        assert!(handle.is::<Base>());
+        assert!(handle.is::<Object>());
+        assert!(!handle.is::<Sibling>());
+    }
 }